@@ -1,15 +1,31 @@
 use std::any::TypeId;
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use iced::futures::channel::mpsc::Sender;
+use iced::futures::future::{AbortHandle, Abortable};
 use iced::futures::never::Never;
 use iced::futures::stream::{unfold, SelectAll};
 use iced::futures::{FutureExt, SinkExt, StreamExt};
 use iced::{subscription, widget, Application, Command, Element, Settings, Subscription};
 use rand::Rng;
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncWriteExt, BufWriter};
 use tokio::sync::mpsc;
 
+const MAX_CONCURRENT_DOWNLOADS: usize = 3;
+const MAX_RETRIES: u32 = 5;
+const BASE_BACKOFF_MS: u64 = 100;
+const MAX_BACKOFF_MS: u64 = 10_000;
+const SPEED_SAMPLE_WINDOW: usize = 5;
+
 fn main() -> iced::Result {
     App::run(Settings::default())
 }
@@ -29,13 +45,32 @@ enum AppState {
 
 struct Download {
     url: String,
+    path: PathBuf,
     progress: f32,
+    downloaded: u64,
+    total: u64,
+    speed: f32,
+    eta: Option<Duration>,
+    failed: bool,
+    queued: bool,
+    paused: bool,
+    retry_attempt: Option<u32>,
+}
+
+fn download_path(url: &str) -> PathBuf {
+    let mut hasher = DefaultHasher::new();
+    url.hash(&mut hasher);
+
+    PathBuf::from(format!("downloads/{:016x}.part", hasher.finish()))
 }
 
 #[derive(Debug, Clone)]
 enum Message {
     DownloaderEvent(DownloaderEvent<usize>),
     StartDownload,
+    CancelDownload(usize),
+    PauseDownload(usize),
+    ResumeDownload(usize),
     Clear,
 }
 
@@ -72,12 +107,54 @@ impl Application for App {
                     return Command::none();
                 };
 
-                let Progress::Advanced(p) = p else {
-                    return Command::none();
-                };
-
-                if let Some(download) = downloads.get_mut(&id) {
-                    download.progress = p
+                match p {
+                    Progress::Cancelled => {
+                        downloads.remove(&id);
+                    }
+                    Progress::Advanced {
+                        percentage,
+                        downloaded,
+                        total,
+                        speed,
+                        eta,
+                    } => {
+                        if let Some(download) = downloads.get_mut(&id) {
+                            download.progress = percentage;
+                            download.downloaded = downloaded;
+                            download.total = total;
+                            download.speed = speed;
+                            download.eta = eta;
+                        }
+                    }
+                    Progress::Errored => {
+                        if let Some(download) = downloads.get_mut(&id) {
+                            download.failed = true;
+                        }
+                    }
+                    Progress::Queued => {
+                        if let Some(download) = downloads.get_mut(&id) {
+                            download.queued = true;
+                            download.paused = false;
+                        }
+                    }
+                    Progress::Started => {
+                        if let Some(download) = downloads.get_mut(&id) {
+                            download.queued = false;
+                            download.paused = false;
+                            download.retry_attempt = None;
+                        }
+                    }
+                    Progress::Retrying { attempt } => {
+                        if let Some(download) = downloads.get_mut(&id) {
+                            download.retry_attempt = Some(attempt);
+                        }
+                    }
+                    Progress::Paused => {
+                        if let Some(download) = downloads.get_mut(&id) {
+                            download.paused = true;
+                        }
+                    }
+                    Progress::Finished => {}
                 }
 
                 Command::none()
@@ -92,18 +169,61 @@ impl Application for App {
                 };
 
                 let url = format!("http://somer.server/files/{}", self.id_counter);
+                let path = download_path(&url);
                 downloads.insert(
                     self.id_counter,
                     Download {
                         url: url.clone(),
+                        path: path.clone(),
                         progress: 0.0,
+                        downloaded: 0,
+                        total: 0,
+                        speed: 0.0,
+                        eta: None,
+                        failed: false,
+                        queued: false,
+                        paused: false,
+                        retry_attempt: None,
                     },
                 );
-                downloader.download(self.id_counter, url.clone());
+                downloader.download(self.id_counter, url, Some(path));
                 self.id_counter += 1;
 
                 Command::none()
             }
+            Message::CancelDownload(id) => {
+                let AppState::Running { downloader, .. } = &self.state else {
+                    return Command::none();
+                };
+
+                downloader.cancel(id);
+
+                Command::none()
+            }
+            Message::PauseDownload(id) => {
+                let AppState::Running { downloader, .. } = &self.state else {
+                    return Command::none();
+                };
+
+                downloader.pause(id);
+
+                Command::none()
+            }
+            Message::ResumeDownload(id) => {
+                let AppState::Running {
+                    downloader,
+                    downloads,
+                } = &self.state
+                else {
+                    return Command::none();
+                };
+
+                if let Some(download) = downloads.get(&id) {
+                    downloader.download(id, download.url.clone(), Some(download.path.clone()));
+                }
+
+                Command::none()
+            }
             Message::Clear => {
                 let AppState::Running { downloads, .. } = &mut self.state else {
                     return Command::none();
@@ -121,13 +241,70 @@ impl Application for App {
             AppState::Init => widget::text("App is initializing...").into(),
             AppState::Running { downloads, .. } => {
                 let downloads: Vec<Element<_>> = downloads
-                    .values()
-                    .map(|d| {
-                        widget::column!(
-                            widget::text(d.url.clone()),
-                            widget::progress_bar(0f32..=100f32, d.progress)
-                        )
-                        .into()
+                    .iter()
+                    .map(|(&id, d)| {
+                        let cancel_btn =
+                            widget::button("Cancel").on_press(Message::CancelDownload(id));
+
+                        if d.failed {
+                            widget::column!(widget::text(d.url.clone()), widget::text("failed"))
+                                .into()
+                        } else if d.paused {
+                            let resume_btn =
+                                widget::button("Resume").on_press(Message::ResumeDownload(id));
+
+                            widget::row!(
+                                widget::column!(
+                                    widget::text(d.url.clone()),
+                                    widget::text("paused")
+                                ),
+                                cancel_btn,
+                                resume_btn
+                            )
+                            .into()
+                        } else if d.queued {
+                            widget::row!(
+                                widget::column!(
+                                    widget::text(d.url.clone()),
+                                    widget::text("waiting...")
+                                ),
+                                cancel_btn
+                            )
+                            .into()
+                        } else if let Some(attempt) = d.retry_attempt {
+                            widget::row!(
+                                widget::column!(
+                                    widget::text(d.url.clone()),
+                                    widget::text(format!("retry {attempt}/{MAX_RETRIES}"))
+                                ),
+                                cancel_btn
+                            )
+                            .into()
+                        } else {
+                            let eta = d
+                                .eta
+                                .map(format_eta)
+                                .unwrap_or_else(|| "calculating...".to_string());
+                            let pause_btn =
+                                widget::button("Pause").on_press(Message::PauseDownload(id));
+
+                            widget::row!(
+                                widget::column!(
+                                    widget::text(d.url.clone()),
+                                    widget::progress_bar(0f32..=100f32, d.progress),
+                                    widget::text(format!(
+                                        "{} / {} — {}/s — {}",
+                                        format_size(d.downloaded),
+                                        format_size(d.total),
+                                        format_size(d.speed as u64),
+                                        eta
+                                    ))
+                                ),
+                                cancel_btn,
+                                pause_btn
+                            )
+                            .into()
+                        }
                     })
                     .collect();
 
@@ -142,34 +319,131 @@ impl Application for App {
     }
 
     fn subscription(&self) -> iced::Subscription<Self::Message> {
-        download_worker().map(Message::DownloaderEvent)
+        download_worker(MAX_CONCURRENT_DOWNLOADS).map(Message::DownloaderEvent)
     }
 }
 
-fn download_worker<I: Copy + Send + 'static>() -> Subscription<DownloaderEvent<I>> {
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f32;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    format!("{:02}:{:02} left", secs / 60, secs % 60)
+}
+
+fn download_worker<I: Copy + Eq + std::hash::Hash + Send + 'static>(
+    limit: usize,
+) -> Subscription<DownloaderEvent<I>> {
     let id = TypeId::of::<Downloader<I>>();
 
-    subscription::channel(id, 128, run)
+    subscription::channel(id, 128, move |sender| run(sender, limit))
 }
 
-async fn run<I: Copy>(mut sender: Sender<DownloaderEvent<I>>) -> Never {
+async fn run<I: Copy + Eq + std::hash::Hash>(
+    mut sender: Sender<DownloaderEvent<I>>,
+    limit: usize,
+) -> Never {
     let (tx, mut rx) = mpsc::channel(32);
     let downloader = Downloader { sender: tx };
 
     let _ = sender.send(DownloaderEvent::Initialized(downloader)).await;
 
     let mut downloads = SelectAll::new();
+    let mut handles: HashMap<I, AbortHandle> = HashMap::new();
+    let mut pause_flags: HashMap<I, Arc<AtomicBool>> = HashMap::new();
+    let mut pending: VecDeque<(I, String, Option<PathBuf>)> = VecDeque::new();
+
+    let start = |downloads: &mut SelectAll<_>,
+                 handles: &mut HashMap<I, AbortHandle>,
+                 pause_flags: &mut HashMap<I, Arc<AtomicBool>>,
+                 id: I,
+                 url: String,
+                 path: Option<PathBuf>| {
+        let (handle, registration) = AbortHandle::new_pair();
+        handles.insert(id, handle);
+
+        let pause = Arc::new(AtomicBool::new(false));
+        pause_flags.insert(id, pause.clone());
+
+        downloads.push(Abortable::new(
+            unfold(
+                State::Ready {
+                    url,
+                    attempt: 0,
+                    path,
+                    pause,
+                },
+                move |state| Box::pin(download(id, state).map(Some)),
+            ),
+            registration,
+        ));
+    };
+
     loop {
         tokio::select! {
             Some(msg) = rx.recv() => {
                 match msg {
-                    DownloaderMessage::Download(id, url) => downloads
-                        .push(unfold(State::Ready(url), move |state| {
-                            Box::pin(download(id, state).map(Some))
-                        })),
+                    DownloaderMessage::Download(id, url, path) => {
+                        let already_in_flight = handles.contains_key(&id)
+                            || pending.iter().any(|(pending_id, ..)| *pending_id == id);
+
+                        if !already_in_flight {
+                            if handles.len() < limit {
+                                start(&mut downloads, &mut handles, &mut pause_flags, id, url, path);
+                            } else {
+                                pending.push_back((id, url, path));
+                                let _ = sender
+                                    .send(DownloaderEvent::Progress(id, Progress::Queued))
+                                    .await;
+                            }
+                        }
+                    }
+                    DownloaderMessage::Cancel(id) => {
+                        pause_flags.remove(&id);
+
+                        if let Some(handle) = handles.remove(&id) {
+                            handle.abort();
+
+                            if let Some((id, url, path)) = pending.pop_front() {
+                                start(&mut downloads, &mut handles, &mut pause_flags, id, url, path);
+                            }
+                        } else {
+                            pending.retain(|(pending_id, ..)| *pending_id != id);
+                        }
+
+                        let _ = sender
+                            .send(DownloaderEvent::Progress(id, Progress::Cancelled))
+                            .await;
+                    }
+                    DownloaderMessage::Pause(id) => {
+                        if let Some(pause) = pause_flags.get(&id) {
+                            pause.store(true, Ordering::SeqCst);
+                        }
+                    }
                 }
             }
             Some((i, p)) = downloads.next() => {
+                if matches!(p, Progress::Finished | Progress::Errored | Progress::Paused) {
+                    if let Some(handle) = handles.remove(&i) {
+                        handle.abort();
+                    }
+                    pause_flags.remove(&i);
+
+                    if let Some((id, url, path)) = pending.pop_front() {
+                        start(&mut downloads, &mut handles, &mut pause_flags, id, url, path);
+                    }
+                }
+
                 let _ = sender.send(DownloaderEvent::Progress(i, p)).await;
             }
         }
@@ -188,61 +462,286 @@ struct Downloader<I> {
 }
 
 impl<I> Downloader<I> {
-    fn download(&self, id: I, url: String) {
-        let _ = self.sender.try_send(DownloaderMessage::Download(id, url));
+    fn download(&self, id: I, url: String, path: Option<PathBuf>) {
+        let _ = self
+            .sender
+            .try_send(DownloaderMessage::Download(id, url, path));
+    }
+
+    fn cancel(&self, id: I) {
+        let _ = self.sender.try_send(DownloaderMessage::Cancel(id));
+    }
+
+    fn pause(&self, id: I) {
+        let _ = self.sender.try_send(DownloaderMessage::Pause(id));
     }
 }
 
 enum DownloaderMessage<I> {
-    Download(I, String),
+    Download(I, String, Option<PathBuf>),
+    Cancel(I),
+    Pause(I),
 }
 
 async fn download<I: Copy>(id: I, state: State) -> ((I, Progress), State) {
     match state {
-        State::Ready(_url) => {
-            let mut rng = rand::thread_rng();
-            let total = rng.gen_range(10_000..50_000);
-            (
-                (id, Progress::Started),
-                State::Downloading {
-                    total,
-                    downloaded: 0,
-                },
-            )
+        State::Ready {
+            url,
+            attempt,
+            path,
+            pause,
+        } => {
+            let existing_len = match &path {
+                Some(path) => fs::metadata(path).await.map(|m| m.len()).unwrap_or(0),
+                None => 0,
+            };
+
+            let mut request = reqwest::Client::new().get(&url);
+            if existing_len > 0 {
+                request = request.header(RANGE, format!("bytes={existing_len}-"));
+            }
+
+            match request.send().await {
+                Ok(response) if response.status() == StatusCode::PARTIAL_CONTENT => {
+                    match (response.content_length(), open_file(&path, true).await) {
+                        (Some(remaining), Ok(file)) => (
+                            (id, Progress::Started),
+                            State::Downloading(Box::new(Downloading {
+                                response,
+                                file,
+                                path,
+                                total: existing_len + remaining,
+                                downloaded: existing_len,
+                                attempt,
+                                samples: VecDeque::new(),
+                                pause,
+                            })),
+                        ),
+                        _ => ((id, Progress::Errored), State::Finished),
+                    }
+                }
+                // The server ignored the Range header; start over from scratch.
+                Ok(response) if response.status().is_success() => {
+                    match (response.content_length(), open_file(&path, false).await) {
+                        (Some(total), Ok(file)) => (
+                            (id, Progress::Started),
+                            State::Downloading(Box::new(Downloading {
+                                response,
+                                file,
+                                path,
+                                total,
+                                downloaded: 0,
+                                attempt,
+                                samples: VecDeque::new(),
+                                pause,
+                            })),
+                        ),
+                        _ => ((id, Progress::Errored), State::Finished),
+                    }
+                }
+                Ok(response) if response.status().is_server_error() => {
+                    retry(id, url, attempt, path, pause).await
+                }
+                Ok(_) => ((id, Progress::Errored), State::Finished),
+                Err(err) if is_retriable(&err) => retry(id, url, attempt, path, pause).await,
+                Err(_) => ((id, Progress::Errored), State::Finished),
+            }
         }
-        State::Downloading { total, downloaded } => {
-            if downloaded <= total {
-                let (chunk_size, sleep) = {
-                    let mut rng = rand::thread_rng();
-                    (rng.gen_range(1_000..5_000), rng.gen_range(100..500))
-                };
+        State::Downloading(downloading) => {
+            let Downloading {
+                mut response,
+                mut file,
+                path,
+                total,
+                downloaded,
+                attempt,
+                mut samples,
+                pause,
+            } = *downloading;
+            if pause.load(Ordering::SeqCst) {
+                if let Some(mut file) = file {
+                    let _ = file.flush().await;
+                }
 
-                tokio::time::sleep(Duration::from_millis(sleep)).await;
+                return ((id, Progress::Paused), State::Paused);
+            }
+
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    if let Some(file) = &mut file {
+                        if file.write_all(&chunk).await.is_err() {
+                            return ((id, Progress::Errored), State::Finished);
+                        }
+                    }
+
+                    let downloaded = downloaded + chunk.len() as u64;
+                    let percentage = (downloaded as f32 / total as f32) * 100.0;
+
+                    samples.push_back((Instant::now(), downloaded));
+                    while samples.len() > SPEED_SAMPLE_WINDOW {
+                        samples.pop_front();
+                    }
+
+                    let speed = match (samples.front(), samples.back()) {
+                        (Some(&(t0, b0)), Some(&(t1, b1))) if t1 > t0 => {
+                            (b1 - b0) as f32 / (t1 - t0).as_secs_f32()
+                        }
+                        _ => 0.0,
+                    };
+
+                    let eta = (speed > 0.0)
+                        .then(|| {
+                            Duration::from_secs_f32(total.saturating_sub(downloaded) as f32 / speed)
+                        });
+
+                    (
+                        (
+                            id,
+                            Progress::Advanced {
+                                percentage,
+                                downloaded,
+                                total,
+                                speed,
+                                eta,
+                            },
+                        ),
+                        State::Downloading(Box::new(Downloading {
+                            response,
+                            file,
+                            path,
+                            total,
+                            downloaded,
+                            attempt,
+                            samples,
+                            pause,
+                        })),
+                    )
+                }
+                Ok(None) => {
+                    if let Some(mut file) = file {
+                        let _ = file.flush().await;
+                    }
 
-                let downloaded = downloaded + chunk_size;
-                let percentage = (downloaded as f32 / total as f32) * 100.0;
+                    ((id, Progress::Finished), State::Finished)
+                }
+                Err(err) if is_retriable(&err) => {
+                    if let Some(mut file) = file {
+                        let _ = file.flush().await;
+                    }
 
-                (
-                    (id, Progress::Advanced(percentage)),
-                    State::Downloading { total, downloaded },
-                )
-            } else {
-                ((id, Progress::Finished), State::Finished)
+                    let url = response.url().to_string();
+                    retry(id, url, attempt, path, pause).await
+                }
+                Err(_) => ((id, Progress::Errored), State::Finished),
             }
         }
-        State::Finished => iced::futures::future::pending().await,
+        State::Paused | State::Finished => iced::futures::future::pending().await,
     }
 }
 
+async fn open_file(
+    path: &Option<PathBuf>,
+    append: bool,
+) -> std::io::Result<Option<BufWriter<File>>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.create(true).write(true);
+    if append {
+        options.append(true);
+    } else {
+        options.truncate(true);
+    }
+
+    let file = options.open(path).await?;
+
+    Ok(Some(BufWriter::new(file)))
+}
+
+fn is_retriable(err: &reqwest::Error) -> bool {
+    err.is_connect()
+        || err.is_timeout()
+        || err
+            .status()
+            .is_some_and(|status| status.is_server_error())
+}
+
+async fn retry<I: Copy>(
+    id: I,
+    url: String,
+    attempt: u32,
+    path: Option<PathBuf>,
+    pause: Arc<AtomicBool>,
+) -> ((I, Progress), State) {
+    if attempt >= MAX_RETRIES {
+        return ((id, Progress::Errored), State::Finished);
+    }
+
+    tokio::time::sleep(backoff(attempt)).await;
+
+    let attempt = attempt + 1;
+    (
+        (id, Progress::Retrying { attempt }),
+        State::Ready {
+            url,
+            attempt,
+            path,
+            pause,
+        },
+    )
+}
+
+fn backoff(attempt: u32) -> Duration {
+    let exp_ms = BASE_BACKOFF_MS.saturating_mul(1 << attempt.min(32));
+    let capped_ms = exp_ms.min(MAX_BACKOFF_MS);
+    let jitter = rand::thread_rng().gen_range(0.5..1.5);
+
+    Duration::from_millis((capped_ms as f32 * jitter) as u64)
+}
+
 #[derive(Debug, Clone)]
 pub enum Progress {
     Started,
-    Advanced(f32),
+    Advanced {
+        percentage: f32,
+        downloaded: u64,
+        total: u64,
+        speed: f32,
+        eta: Option<Duration>,
+    },
     Finished,
+    Errored,
+    Cancelled,
+    Queued,
+    Retrying { attempt: u32 },
+    Paused,
 }
 
 pub enum State {
-    Ready(String),
-    Downloading { total: u64, downloaded: u64 },
+    Ready {
+        url: String,
+        attempt: u32,
+        path: Option<PathBuf>,
+        pause: Arc<AtomicBool>,
+    },
+    Downloading(Box<Downloading>),
+    Paused,
     Finished,
 }
+
+pub struct Downloading {
+    response: reqwest::Response,
+    file: Option<BufWriter<File>>,
+    path: Option<PathBuf>,
+    total: u64,
+    downloaded: u64,
+    attempt: u32,
+    samples: VecDeque<(Instant, u64)>,
+    pause: Arc<AtomicBool>,
+}